@@ -0,0 +1,192 @@
+use super::*;
+use super::num::AllocatedNum;
+use super::boolean::Boolean;
+use ::jubjub::JubjubEngine;
+use ::pedersen_hash::Personalization;
+use bellman::{
+    ConstraintSystem,
+    SynthesisError
+};
+
+/// Computes the root of a Sapling incremental Merkle tree in-circuit, given
+/// a leaf and its authentication path, so spend-style circuits don't have
+/// to hand-roll the per-level `pedersen_hash` loop themselves.
+///
+/// `position_bits[d]` is `true` when the node at depth `d` belongs on the
+/// right of its sibling `auth_path[d]`; it picks which operand comes first
+/// in that level's preimage.
+pub fn merkle_root<E: JubjubEngine, CS>(
+    mut cs: CS,
+    leaf: &AllocatedNum<E>,
+    position_bits: &[Boolean],
+    auth_path: &[AllocatedNum<E>],
+    params: &E::Params
+) -> Result<AllocatedNum<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    assert_eq!(position_bits.len(), auth_path.len());
+
+    let mut cur = leaf.clone();
+
+    for (i, (sibling, position_bit)) in auth_path.iter().zip(position_bits.iter()).enumerate() {
+        let mut cs = cs.namespace(|| format!("merkle tree hash {}", i));
+
+        // Swap the current node and its sibling into (left, right) order
+        // according to `position_bit`, so the preimage is always the left
+        // node followed by the right node.
+        let (left, right) = conditionally_swap(
+            cs.namespace(|| "conditional swap"),
+            &cur,
+            sibling,
+            position_bit
+        )?;
+
+        let mut preimage = vec![];
+        preimage.extend(left.to_bits_le_strict(cs.namespace(|| "left bits"))?);
+        preimage.extend(right.to_bits_le_strict(cs.namespace(|| "right bits"))?);
+
+        let node = pedersen_hash::pedersen_hash(
+            cs.namespace(|| "computation of pedersen hash"),
+            Personalization::MerkleTree(i),
+            &preimage,
+            params
+        )?;
+
+        cur = node.get_x().clone();
+    }
+
+    Ok(cur)
+}
+
+/// Conditionally swaps `a` and `b`, returning `(b, a)` when `condition` is
+/// true and `(a, b)` otherwise.
+fn conditionally_swap<E: JubjubEngine, CS>(
+    mut cs: CS,
+    a: &AllocatedNum<E>,
+    b: &AllocatedNum<E>,
+    condition: &Boolean
+) -> Result<(AllocatedNum<E>, AllocatedNum<E>), SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let left = AllocatedNum::alloc(cs.namespace(|| "conditional swap left"), || {
+        if *condition.get_value().get()? {
+            Ok(*b.get_value().get()?)
+        } else {
+            Ok(*a.get_value().get()?)
+        }
+    })?;
+
+    cs.enforce(
+        || "conditional swap left is correct",
+        |lc| lc + a.get_variable() - b.get_variable(),
+        |_| condition.lc(CS::one(), E::Fr::one()),
+        |lc| lc + a.get_variable() - left.get_variable()
+    );
+
+    let right = AllocatedNum::alloc(cs.namespace(|| "conditional swap right"), || {
+        if *condition.get_value().get()? {
+            Ok(*a.get_value().get()?)
+        } else {
+            Ok(*b.get_value().get()?)
+        }
+    })?;
+
+    cs.enforce(
+        || "conditional swap right is correct",
+        |lc| lc + b.get_variable() - a.get_variable(),
+        |_| condition.lc(CS::one(), E::Fr::one()),
+        |lc| lc + b.get_variable() - right.get_variable()
+    );
+
+    Ok((left, right))
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{SeedableRng, Rng, XorShiftRng};
+    use super::*;
+    use ::circuit::test::*;
+    use ::circuit::boolean::{Boolean, AllocatedBit};
+    use ::circuit::num::AllocatedNum;
+    use ::jubjub::JubjubBls12;
+    use ::pedersen_hash::{pedersen_hash, Personalization};
+    use pairing::bls12_381::{Bls12, Fr};
+    use pairing::{PrimeField, PrimeFieldRepr};
+
+    #[test]
+    fn test_merkle_root() {
+        let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &JubjubBls12::new();
+
+        for depth in 0..5 {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            let leaf_value: Fr = rng.gen();
+            let leaf = AllocatedNum::alloc(cs.namespace(|| "leaf"), || Ok(leaf_value)).unwrap();
+
+            let mut position_values = vec![];
+            let mut position_bits = vec![];
+            let mut auth_path_values = vec![];
+            let mut auth_path = vec![];
+
+            for i in 0..depth {
+                let sibling_value: Fr = rng.gen();
+                auth_path_values.push(sibling_value);
+                auth_path.push(
+                    AllocatedNum::alloc(cs.namespace(|| format!("sibling {}", i)), || Ok(sibling_value)).unwrap()
+                );
+
+                let position: bool = rng.gen();
+                position_values.push(position);
+                position_bits.push(
+                    Boolean::from(
+                        AllocatedBit::alloc(cs.namespace(|| format!("position {}", i)), Some(position)).unwrap()
+                    )
+                );
+            }
+
+            let root = merkle_root(
+                cs.namespace(|| "merkle root"),
+                &leaf,
+                &position_bits,
+                &auth_path,
+                params
+            ).unwrap();
+
+            assert!(cs.is_satisfied());
+
+            // Recompute the root outside the circuit and check it matches.
+            let mut cur = leaf_value;
+            for i in 0..depth {
+                let sibling_value = auth_path_values[i];
+                let (left, right) = if position_values[i] {
+                    (sibling_value, cur)
+                } else {
+                    (cur, sibling_value)
+                };
+
+                let mut preimage = vec![];
+                preimage.extend(le_bits(&left));
+                preimage.extend(le_bits(&right));
+
+                cur = pedersen_hash::<Bls12, _>(
+                    Personalization::MerkleTree(i),
+                    preimage,
+                    params
+                ).into_xy().0;
+            }
+
+            assert_eq!(root.get_value().unwrap(), cur);
+        }
+    }
+
+    fn le_bits(value: &Fr) -> Vec<bool> {
+        let mut repr = value.into_repr();
+        let mut bits = vec![];
+        for _ in 0..Fr::NUM_BITS {
+            bits.push(repr.as_ref()[0] & 1 == 1);
+            repr.shr(1);
+        }
+        bits
+    }
+}
@@ -0,0 +1,11 @@
+pub use bellman::SynthesisError;
+
+pub mod boolean;
+pub mod ecc;
+pub mod lookup;
+pub mod num;
+pub mod pedersen_hash;
+pub mod merkle;
+
+#[cfg(test)]
+pub mod test;
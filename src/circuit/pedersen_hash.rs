@@ -47,13 +47,17 @@ pub fn pedersen_hash<E: JubjubEngine, CS>(
             let b = bits.next().unwrap_or(&boolean_false);
             let c = bits.next().unwrap_or(&boolean_false);
 
-            let tmp = lookup3_xy_with_conditional_negation(
+            // Windows whose three bits are all `Boolean::Constant` are not
+            // folded into a constant point: every window, constant or not,
+            // goes through the same lookup. The lookup only skips its
+            // `precomp` constraint when `a` or `b` is constant.
+            let (x, y) = lookup3_xy_with_conditional_negation(
                 cs.namespace(|| format!("segment {}, window {}", segment_i, window_i)),
                 &[a.clone(), b.clone(), c.clone()],
                 &segment_windows[0]
             )?;
 
-            let tmp = MontgomeryPoint::interpret_unchecked(tmp.0, tmp.1);
+            let tmp = MontgomeryPoint::interpret_unchecked(x, y);
 
             match segment_result {
                 None => {
@@ -110,38 +114,82 @@ pub fn pedersen_hash<E: JubjubEngine, CS>(
     Ok(hash_result.unwrap())
 }
 
+/// Computes a Pedersen commitment to `bits` in-circuit: the Pedersen hash
+/// of `bits` added to a fixed-base multiplication of `randomness` against
+/// `randomness_generator`. Mirrors `pedersen_hash::pedersen_commitment`.
+pub fn pedersen_commitment<E: JubjubEngine, CS>(
+    mut cs: CS,
+    personalization: Personalization,
+    bits: &[Boolean],
+    randomness_generator: FixedGenerators,
+    randomness: &[Boolean],
+    params: &E::Params
+) -> Result<EdwardsPoint<E>, SynthesisError>
+    where CS: ConstraintSystem<E>
+{
+    let h = pedersen_hash(
+        cs.namespace(|| "pedersen hash"),
+        personalization,
+        bits,
+        params
+    )?;
+
+    let r_g = ecc::fixed_base_multiplication(
+        cs.namespace(|| "randomness generator"),
+        randomness_generator,
+        randomness,
+        params
+    )?;
+
+    h.add(cs.namespace(|| "add randomness"), &r_g, params)
+}
+
 #[cfg(test)]
 mod test {
     use rand::{SeedableRng, Rng, XorShiftRng};
     use super::*;
     use ::circuit::test::*;
     use ::circuit::boolean::{Boolean, AllocatedBit};
-    use pairing::bls12_381::{Bls12, Fr};
-    use pairing::PrimeField;
-
-    /// Predict the number of constraints of a Pedersen hash
-    fn ph_num_constraints(input_bits: usize) -> usize {
-
-        // Account for the 6 personalization bits.
-        let personalized_bits = 6 + input_bits;
-        // Constant booleans in the personalization and padding don't need lookup "precomp" constraints.
-        let precomputed_booleans = 2 + (personalized_bits % 3 == 1) as usize;
-
-        // Count chunks and segments with ceiling division
-        let chunks = (personalized_bits + 3 - 1) / 3;
+    use pairing::bls12_381::{Bls12, Fs};
+    use pairing::{PrimeField, PrimeFieldRepr};
+
+    /// Predict the number of constraints of a Pedersen hash over an input
+    /// whose bits may be a mix of variable (`is_constant[i] == false`) and
+    /// `Boolean::Constant` (`is_constant[i] == true`) bits, as seen after
+    /// the 6-bit personalization prefix and any trailing zero padding.
+    /// `pedersen_hash` doesn't fold constant windows into constant points,
+    /// so the only saving from constant bits is the lookup's `precomp`
+    /// constraint, skipped when a window's `a` or `b` bit is constant.
+    fn ph_num_constraints(is_constant: &[bool]) -> usize {
+
+        // The 6 personalization bits and any padding out to a multiple of
+        // 3 are always constant.
+        let personalized_bits = 6 + is_constant.len();
+        let padding = (3 - personalized_bits % 3) % 3;
+
+        let all_constants: Vec<bool> = ::std::iter::repeat(true).take(6)
+            .chain(is_constant.iter().cloned())
+            .chain(::std::iter::repeat(true).take(padding))
+            .collect();
+
+        let chunks = all_constants.len() / 3;
         let segments = (chunks + 63 - 1) / 63;
         let all_but_last_segments = segments - 1;
         let last_chunks = chunks - all_but_last_segments * 63;
 
         // Constraints per operation
-        let lookup_chunk = 2;
         let add_chunks = 3;      // Montgomery addition
         let convert_segment = 2; // Conversion to Edwards
         let add_segments = 6;    // Edwards addition
 
+        // Each window's lookup costs one constraint, plus one "precomp"
+        // constraint unless its `a` or `b` bit is constant.
+        let lookup_cost: usize = all_constants.chunks(3)
+            .map(|w| 1 + (!w[0] && !w[1]) as usize)
+            .sum();
+
         return
-            (chunks) * lookup_chunk
-            - precomputed_booleans
+            lookup_cost
             + segments * convert_segment
             + all_but_last_segments * ((63 - 1) * add_chunks + add_segments)
             + (last_chunks - 1) * add_chunks;
@@ -173,13 +221,56 @@ mod test {
             assert!(cs.is_satisfied());
 
             let bitness_constraints = n_bits;
-            let ph_constraints = ph_num_constraints(n_bits);
+            let ph_constraints = ph_num_constraints(&vec![false; n_bits]);
             assert_eq!(cs.num_constraints(), bitness_constraints + ph_constraints);
             // The main use case
             if n_bits == 510 { assert_eq!(cs.num_constraints(), 510 + 867) };
         }
     }
 
+    #[test]
+    fn test_pedersen_hash_constraints_with_constant_bits() {
+        // Mix `Boolean::Constant` bits in with variable (allocated) bits
+        // and check `ph_num_constraints`'s accounting still matches: a
+        // constant bit saves its bitness constraint, and a constant `a` or
+        // `b` bit also saves its window's lookup `precomp` constraint.
+        let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &JubjubBls12::new();
+
+        for &n_bits in [3*63-6, 255, 510].iter() {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            // Every fifth bit (landing in the `a`, `c` and `b` slots of its
+            // window in turn) is fixed at circuit-synthesis time; the
+            // remainder are ordinary witnesses.
+            let is_constant: Vec<bool> = (0..n_bits).map(|i| i % 5 == 0).collect();
+            let input: Vec<bool> = (0..n_bits).map(|_| rng.gen()).collect();
+
+            let input_bools: Vec<Boolean> = input.iter().zip(is_constant.iter()).enumerate().map(|(i, (&b, &is_const))| {
+                if is_const {
+                    Boolean::constant(b)
+                } else {
+                    Boolean::from(
+                        AllocatedBit::alloc(cs.namespace(|| format!("input {}", i)), Some(b)).unwrap()
+                    )
+                }
+            }).collect();
+
+            pedersen_hash(
+                cs.namespace(|| "pedersen hash"),
+                Personalization::NoteCommitment,
+                &input_bools,
+                params
+            ).unwrap();
+
+            assert!(cs.is_satisfied());
+
+            let bitness_constraints = is_constant.iter().filter(|&&c| !c).count();
+            let ph_constraints = ph_num_constraints(&is_constant);
+            assert_eq!(cs.num_constraints(), bitness_constraints + ph_constraints);
+        }
+    }
+
     #[test]
     fn test_pedersen_hash() {
         let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
@@ -227,4 +318,67 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_pedersen_commitment() {
+        let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &JubjubBls12::new();
+
+        for length in 0..100 {
+            let input: Vec<bool> = (0..length).map(|_| rng.gen()).collect();
+
+            // Draw a canonical scalar directly, rather than a raw bit
+            // string that could exceed the Fs modulus, and derive the
+            // circuit's randomness bits from its own little-endian repr so
+            // both sides are reducing the exact same scalar.
+            let r: Fs = rng.gen();
+            let randomness = le_bits(&r);
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            let input_bools: Vec<Boolean> = input.iter().enumerate().map(|(i, b)| {
+                Boolean::from(
+                    AllocatedBit::alloc(cs.namespace(|| format!("input {}", i)), Some(*b)).unwrap()
+                )
+            }).collect();
+
+            let randomness_bools: Vec<Boolean> = randomness.iter().enumerate().map(|(i, b)| {
+                Boolean::from(
+                    AllocatedBit::alloc(cs.namespace(|| format!("randomness {}", i)), Some(*b)).unwrap()
+                )
+            }).collect();
+
+            let res = pedersen_commitment(
+                cs.namespace(|| "pedersen commitment"),
+                Personalization::NoteCommitment,
+                &input_bools,
+                FixedGenerators::NoteCommitmentRandomness,
+                &randomness_bools,
+                params
+            ).unwrap();
+
+            assert!(cs.is_satisfied());
+
+            let expected = ::pedersen_hash::pedersen_commitment::<Bls12, _>(
+                Personalization::NoteCommitment,
+                input.into_iter(),
+                FixedGenerators::NoteCommitmentRandomness,
+                r,
+                params
+            ).into_xy();
+
+            assert_eq!(res.get_x().get_value().unwrap(), expected.0);
+            assert_eq!(res.get_y().get_value().unwrap(), expected.1);
+        }
+    }
+
+    fn le_bits(value: &Fs) -> Vec<bool> {
+        let mut repr = value.into_repr();
+        let mut bits = vec![];
+        for _ in 0..Fs::NUM_BITS {
+            bits.push(repr.as_ref()[0] & 1 == 1);
+            repr.shr(1);
+        }
+        bits
+    }
 }
@@ -5,6 +5,12 @@ extern crate digest;
 extern crate rand;
 extern crate byteorder;
 
+// Enables a parallel fold over the independent per-segment scalar
+// multiplications in the non-circuit `pedersen_hash`. Off by default; turn
+// on with the `multicore` feature.
+#[cfg(feature = "multicore")]
+extern crate rayon;
+
 #[cfg(test)]
 #[macro_use]
 extern crate hex_literal;
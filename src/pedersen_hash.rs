@@ -1,6 +1,15 @@
 use jubjub::*;
 use pairing::*;
 
+// `params.pedersen_hash_exp_table()` is expected to hold, for each
+// generator `G_i`, one table per window of `E::Fs`, where window `j`'s
+// entries are `table[i][j][k] = (k * 2^{w_0 + ... + w_{j-1}}) * G_i` for
+// `k` ranging over that window's own width `w_j = log2(table[i][j].len())`.
+// `table_lookup` below reads the window width back out of each window's
+// length rather than assuming a width of its own, so it can't silently
+// desynchronize from whatever width `JubjubBls12::new()` built the table
+// with.
+
 #[derive(Clone, Copy)]
 pub enum Personalization {
     NoteCommitment,
@@ -29,10 +38,25 @@ pub fn pedersen_hash<E, I>(
     where I: IntoIterator<Item=bool>,
           E: JubjubEngine
 {
-    let mut bits = personalization.get_bits().into_iter().chain(bits.into_iter());
+    let accs = segment_accumulators::<E, _>(personalization, bits, params);
 
-    let mut result = edwards::Point::zero();
-    let mut generators = params.pedersen_hash_generators().iter();
+    fold_segments::<E>(&accs, params)
+}
+
+/// Splits `personalization`'s bits followed by `bits` into the per-segment
+/// scalar accumulators that `pedersen_hash` would otherwise fold into a
+/// point one at a time. Segments are independent of one another, so this
+/// is the boundary `fold_segments` uses to parallelize the hash.
+fn segment_accumulators<E, I>(
+    personalization: Personalization,
+    bits: I,
+    params: &E::Params
+) -> Vec<E::Fs>
+    where I: IntoIterator<Item=bool>,
+          E: JubjubEngine
+{
+    let mut bits = personalization.get_bits().into_iter().chain(bits.into_iter());
+    let mut accs = vec![];
 
     loop {
         let mut acc = E::Fs::zero();
@@ -88,17 +112,147 @@ pub fn pedersen_hash<E, I>(
             break;
         }
 
-        let mut tmp = generators.next().expect("we don't have enough generators").clone();
-        tmp = tmp.mul(acc, params);
+        accs.push(acc);
+    }
+
+    accs
+}
+
+/// Folds the per-segment scalar accumulators produced by
+/// `segment_accumulators` into the final hash point, sequentially. Kept
+/// unconditionally (rather than only under `#[cfg(not(feature =
+/// "multicore"))]`) so tests can compare it directly against
+/// `fold_segments_parallel` instead of going through `fold_segments`,
+/// whose dispatch would otherwise make such a comparison tautological.
+fn fold_segments_sequential<E: JubjubEngine>(
+    accs: &[E::Fs],
+    params: &E::Params
+) -> edwards::Point<E, PrimeOrder>
+{
+    let mut result = edwards::Point::zero();
+    let mut exp_tables = params.pedersen_hash_exp_table().iter();
+
+    for acc in accs {
+        let exp_table = exp_tables.next().expect("we don't have enough generators");
+        let tmp = table_lookup::<E>(*acc, exp_table, params);
         result = result.add(&tmp, params);
     }
 
     result
 }
 
+/// Folds the per-segment scalar accumulators produced by
+/// `segment_accumulators` into the final hash point, evaluating the
+/// independent `table_lookup` calls across segments in parallel and then
+/// reducing the resulting points with `add`. Point addition on the
+/// prime-order subgroup is commutative and associative, so this always
+/// yields the exact same point as `fold_segments_sequential`.
+#[cfg(feature = "multicore")]
+fn fold_segments_parallel<E: JubjubEngine>(
+    accs: &[E::Fs],
+    params: &E::Params
+) -> edwards::Point<E, PrimeOrder>
+{
+    use rayon::prelude::*;
+
+    let exp_tables = params.pedersen_hash_exp_table();
+
+    // `zip` silently truncates to the shorter side; the sequential fold
+    // panics via `exp_tables.next().expect(..)` if there aren't enough
+    // generators for every segment, so mirror that check here rather than
+    // dropping segments and returning the wrong point.
+    assert!(accs.len() <= exp_tables.len(), "we don't have enough generators");
+
+    accs.par_iter()
+        .zip(exp_tables.par_iter())
+        .map(|(acc, exp_table)| table_lookup::<E>(*acc, exp_table, params))
+        .reduce(edwards::Point::zero, |a, b| a.add(&b, params))
+}
+
+/// Picks the sequential or parallel fold depending on the `multicore`
+/// feature; see `test_pedersen_hash_parallel_matches_sequential` for the
+/// proof that the two always agree.
+#[cfg(not(feature = "multicore"))]
+fn fold_segments<E: JubjubEngine>(
+    accs: &[E::Fs],
+    params: &E::Params
+) -> edwards::Point<E, PrimeOrder>
+{
+    fold_segments_sequential::<E>(accs, params)
+}
+
+#[cfg(feature = "multicore")]
+fn fold_segments<E: JubjubEngine>(
+    accs: &[E::Fs],
+    params: &E::Params
+) -> edwards::Point<E, PrimeOrder>
+{
+    fold_segments_parallel::<E>(accs, params)
+}
+
+/// Computes a Pedersen commitment to `bits`: the Pedersen hash of `bits`
+/// blinded by `r` times `randomness_generator`. This is the primitive
+/// Sapling note and value commitments are built from.
+pub fn pedersen_commitment<E, I>(
+    personalization: Personalization,
+    bits: I,
+    randomness_generator: FixedGenerators,
+    r: E::Fs,
+    params: &E::Params
+) -> edwards::Point<E, PrimeOrder>
+    where I: IntoIterator<Item=bool>,
+          E: JubjubEngine
+{
+    let h = pedersen_hash(personalization, bits, params);
+
+    let r_g = params
+        .generator(randomness_generator)
+        .mul(r, params);
+
+    h.add(&r_g, params)
+}
+
+/// Evaluate `acc * G` against a precomputed fixed-base window table for the
+/// (fixed) generator `G`, as built by `params.pedersen_hash_exp_table()`.
+///
+/// `acc` is decomposed into little-endian base-`2^w_j` digits `d_0, d_1,
+/// ...`, where each window's own width `w_j = log2(table[j].len())` is read
+/// back out of the table rather than assumed, so a table built with a
+/// different width than this function expects is decoded correctly rather
+/// than silently misread; the result is the sum of `table[j][d_j]` over all
+/// `j`, skipping windows whose digit is zero (`table[j][0]` is the
+/// identity). Since `G` never changes, this replaces the variable-time
+/// double-and-add in `mul` with a handful of table lookups and additions.
+fn table_lookup<E: JubjubEngine>(
+    acc: E::Fs,
+    table: &[Vec<edwards::Point<E, PrimeOrder>>],
+    params: &E::Params
+) -> edwards::Point<E, PrimeOrder>
+{
+    let mut result = edwards::Point::zero();
+    let mut acc_repr = acc.into_repr();
+
+    for window in table {
+        assert!(window.len().is_power_of_two(), "window table entries must be a power of two");
+
+        let window_size = window.len().trailing_zeros();
+        let window_mask = (window.len() as u64) - 1;
+        let digit = (acc_repr.as_ref()[0] & window_mask) as usize;
+
+        if digit != 0 {
+            result = result.add(&window[digit], params);
+        }
+
+        acc_repr.shr(window_size);
+    }
+
+    result
+}
+
 #[cfg(test)]
 pub mod test {
 
+    use rand::{SeedableRng, Rng, XorShiftRng};
     use pairing::bls12_381::{Bls12};
     use super::*;
 
@@ -133,4 +287,34 @@ pub mod test {
         assert_eq!(x.to_string(), v.hash_x);
         assert_eq!(y.to_string(), v.hash_y);
     }
+
+    /// `fold_segments_parallel` must produce the same point as
+    /// `fold_segments_sequential`, since segment addition on the
+    /// prime-order subgroup is commutative and associative. Only built
+    /// with `--features multicore`, since that's the only configuration
+    /// where `fold_segments_parallel` exists; without it there's no
+    /// second implementation to compare against, and comparing
+    /// `fold_segments_sequential` with itself (or with `pedersen_hash`,
+    /// which just calls it) would prove nothing.
+    #[cfg(feature = "multicore")]
+    #[test]
+    fn test_pedersen_hash_parallel_matches_sequential() {
+        let params = &JubjubBls12::new();
+        let mut rng = XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for &length in [0usize, 1, 5, 64, 189, 192, 381, 600].iter() {
+            let input: Vec<bool> = (0..length).map(|_| rng.gen()).collect();
+
+            let accs = segment_accumulators::<Bls12, _>(
+                Personalization::MerkleTree(3),
+                input.into_iter(),
+                params
+            );
+
+            let sequential = fold_segments_sequential::<Bls12>(&accs, params);
+            let parallel = fold_segments_parallel::<Bls12>(&accs, params);
+
+            assert_eq!(sequential.into_xy(), parallel.into_xy());
+        }
+    }
 }